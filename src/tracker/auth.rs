@@ -1,25 +1,45 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::panic::Location;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use derive_more::Display;
+use hmac::{Hmac, Mac};
 use log::debug;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use thiserror::Error;
 
 use crate::located_error::LocatedError;
 use crate::protocol::clock::{Current, DurationSinceUnixEpoch, Time, TimeNow};
 use crate::protocol::common::AUTH_KEY_LENGTH;
+use crate::protocol::info_hash::InfoHash;
+
+/// Minimum accepted length, in bytes, for a [`HmacSecret`].
+pub const MIN_HMAC_SECRET_LEN: usize = 32;
 
 #[must_use]
 /// # Panics
 ///
-/// It would panic if the `lifetime: Duration` + Duration is more than `Duration::MAX`.
+/// Will panic if `lifetime` added to the current time would overflow the clock's max
+/// representable time. Use [`try_generate`] to handle that case without panicking.
 pub fn generate(lifetime: Duration) -> ExpiringKey {
+    try_generate(lifetime).expect("lifetime overflowed the clock's max representable time")
+}
+
+/// # Errors
+///
+/// Will return `Error::InvalidLifetime` if `lifetime` added to the current time would
+/// overflow the clock's max representable time.
+pub fn try_generate(lifetime: Duration) -> Result<ExpiringKey, Error> {
     let random_id: String = thread_rng()
         .sample_iter(&Alphanumeric)
         .take(AUTH_KEY_LENGTH)
@@ -28,49 +48,161 @@ pub fn generate(lifetime: Duration) -> ExpiringKey {
 
     debug!("Generated key: {}, valid for: {:?} seconds", random_id, lifetime);
 
+    let valid_until = Current::add(&lifetime).map_err(|_| Error::InvalidLifetime { lifetime })?;
+
+    Ok(ExpiringKey {
+        key: random_id.parse::<Key>().unwrap(),
+        caveats: vec![Caveat::TimeBefore(valid_until)],
+    })
+}
+
+/// Mints a key with no built-in expiry caveat, so it never expires.
+#[must_use]
+pub fn generate_permanent() -> ExpiringKey {
+    let random_id: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(AUTH_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+
+    debug!("Generated permanent key: {}", random_id);
+
     ExpiringKey {
         key: random_id.parse::<Key>().unwrap(),
-        valid_until: Current::add(&lifetime).unwrap(),
+        caveats: Vec::new(),
     }
 }
 
+/// Announce-time facts a [`Caveat`] may need to check a key's scope against.
+pub struct VerificationContext<'a> {
+    pub info_hash: &'a InfoHash,
+    pub peer_ip: IpAddr,
+}
+
 /// # Errors
 ///
-/// Will return `Error::KeyExpired` if `auth_key.valid_until` is past the `current_time`.
+/// Will return `Error::KeyExpired` if the key's built-in expiry caveat is in the past.
 ///
-/// Will return `Error::KeyInvalid` if `auth_key.valid_until` is past the `None`.
-pub fn verify(auth_key: &ExpiringKey) -> Result<(), Error> {
-    let current_time: DurationSinceUnixEpoch = Current::now();
+/// Will return `Error::CaveatNotSatisfied` if any other caveat does not hold for `context`.
+pub fn verify(auth_key: &ExpiringKey, context: &VerificationContext<'_>) -> Result<(), Error> {
+    for caveat in &auth_key.caveats {
+        let satisfied = match caveat {
+            Caveat::TimeBefore(limit) => Current::now() <= *limit,
+            Caveat::TimeAfter(limit) => Current::now() > *limit,
+            Caveat::InfoHash(expected) => context.info_hash == expected,
+            Caveat::PeerIp(cidr) => cidr.contains(context.peer_ip),
+        };
 
-    if auth_key.valid_until < current_time {
-        Err(Error::KeyExpired {
-            location: Location::caller(),
-        })
-    } else {
-        Ok(())
+        if !satisfied {
+            return match caveat {
+                Caveat::TimeBefore(_) => Err(Error::KeyExpired {
+                    location: Location::caller(),
+                }),
+                _ => Err(Error::CaveatNotSatisfied {
+                    caveat: caveat.clone(),
+                    location: Location::caller(),
+                }),
+            };
+        }
+    }
+
+    Ok(())
+}
+
+/// A first-party caveat, following the macaroon notion of a predicate baked into the
+/// token itself: every caveat on a key must hold for `verify` to succeed.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum Caveat {
+    /// `time <= limit`, so a key is still valid at exactly `limit`. The built-in expiry
+    /// caveat added by `generate`.
+    TimeBefore(DurationSinceUnixEpoch),
+    /// `time > limit`. Restricts a key to only become valid after a given instant.
+    TimeAfter(DurationSinceUnixEpoch),
+    /// `info_hash = hash`. Restricts a key to announcing a single torrent.
+    InfoHash(InfoHash),
+    /// `peer_ip = cidr`. Restricts a key to peers announcing from a given IP range.
+    PeerIp(IpCidr),
+}
+
+/// A CIDR block used by [`Caveat::PeerIp`] to scope a key to a range of announcing IPs.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy)]
+pub struct IpCidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(u32::from(32 - self.prefix_len)).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(u32::from(128 - self.prefix_len)).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseIpCidrError;
+
+impl FromStr for IpCidr {
+    type Err = ParseIpCidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = s.split_once('/').ok_or(ParseIpCidrError)?;
+
+        let network: IpAddr = network.parse().map_err(|_| ParseIpCidrError)?;
+        let prefix_len: u8 = prefix_len.parse().map_err(|_| ParseIpCidrError)?;
+
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_prefix_len {
+            return Err(ParseIpCidrError);
+        }
+
+        Ok(Self { network, prefix_len })
+    }
+}
+
+impl std::fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct ExpiringKey {
     pub key: Key,
-    pub valid_until: DurationSinceUnixEpoch,
+    pub caveats: Vec<Caveat>,
 }
 
 impl std::fmt::Display for ExpiringKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "key: `{}`, valid until `{}`",
-            self.key,
-            DateTime::<Utc>::from_utc(
-                NaiveDateTime::from_timestamp(
-                    i64::try_from(self.valid_until.as_secs()).expect("Overflow of i64 seconds, very future!"),
-                    self.valid_until.subsec_nanos(),
-                ),
-                Utc
-            )
-        )
+        let valid_until = self.caveats.iter().find_map(|caveat| match caveat {
+            Caveat::TimeBefore(limit) => Some(*limit),
+            _ => None,
+        });
+
+        match valid_until {
+            Some(valid_until) => write!(
+                f,
+                "key: `{}`, valid until `{}`",
+                self.key,
+                DateTime::<Utc>::from_utc(
+                    NaiveDateTime::from_timestamp(
+                        i64::try_from(valid_until.as_secs()).expect("Overflow of i64 seconds, very future!"),
+                        valid_until.subsec_nanos(),
+                    ),
+                    Utc
+                )
+            ),
+            None => write!(f, "key: `{}`, valid until never", self.key),
+        }
     }
 }
 
@@ -99,6 +231,397 @@ impl FromStr for Key {
     }
 }
 
+type HmacSha256 = Hmac<Sha256>;
+
+/// The server secret used to sign and verify [`SignedKey`] tokens.
+///
+/// Kept server-side only; never embedded in the token itself.
+#[derive(Clone)]
+pub struct HmacSecret(Vec<u8>);
+
+impl HmacSecret {
+    /// # Errors
+    ///
+    /// Will return `Error::HmacSecretTooShort` if `secret` is shorter than
+    /// [`MIN_HMAC_SECRET_LEN`] bytes.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Result<Self, Error> {
+        let secret = secret.into();
+
+        if secret.len() < MIN_HMAC_SECRET_LEN {
+            return Err(Error::HmacSecretTooShort { min: MIN_HMAC_SECRET_LEN });
+        }
+
+        Ok(Self(secret))
+    }
+}
+
+/// A self-contained, stateless auth key: `<random_id>.<valid_until_unix_secs>.<base64(hmac)>`.
+///
+/// Unlike [`ExpiringKey`], a `SignedKey` carries its own signature, so `verify_signed` can
+/// validate it without a store round-trip.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct SignedKey {
+    random_id: String,
+    valid_until: DurationSinceUnixEpoch,
+    signature: Vec<u8>,
+}
+
+impl std::fmt::Display for SignedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            self.random_id,
+            self.valid_until.as_secs(),
+            BASE64.encode(&self.signature)
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseSignedKeyError;
+
+impl FromStr for SignedKey {
+    type Err = ParseSignedKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.');
+
+        let random_id = parts.next().ok_or(ParseSignedKeyError)?;
+        let valid_until = parts.next().ok_or(ParseSignedKeyError)?;
+        let signature = parts.next().ok_or(ParseSignedKeyError)?;
+
+        if parts.next().is_some() || random_id.len() != AUTH_KEY_LENGTH {
+            return Err(ParseSignedKeyError);
+        }
+
+        let valid_until = valid_until.parse::<u64>().map_err(|_| ParseSignedKeyError)?;
+        let signature = BASE64.decode(signature).map_err(|_| ParseSignedKeyError)?;
+
+        Ok(Self {
+            random_id: random_id.to_string(),
+            valid_until: Duration::from_secs(valid_until),
+            signature,
+        })
+    }
+}
+
+/// # Errors
+///
+/// Will return `Error::InvalidLifetime` if `lifetime` added to the current time would
+/// overflow the clock's max representable time.
+pub fn generate_signed(lifetime: Duration, secret: &HmacSecret) -> Result<SignedKey, Error> {
+    let random_id: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(AUTH_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+
+    let valid_until = Current::add(&lifetime).map_err(|_| Error::InvalidLifetime { lifetime })?;
+    let signature = sign(secret, &random_id, valid_until);
+
+    Ok(SignedKey {
+        random_id,
+        valid_until,
+        signature,
+    })
+}
+
+/// # Errors
+///
+/// Will return `Error::InvalidSignature` if the embedded signature does not match the one
+/// recomputed from `secret`.
+///
+/// Will return `Error::KeyExpired` if the current time is past `valid_until`.
+pub fn verify_signed(signed_key: &SignedKey, secret: &HmacSecret) -> Result<(), Error> {
+    let expected_signature = sign(secret, &signed_key.random_id, signed_key.valid_until);
+
+    if !constant_time_eq(&expected_signature, &signed_key.signature) {
+        return Err(Error::InvalidSignature {
+            location: Location::caller(),
+        });
+    }
+
+    if signed_key.valid_until < Current::now() {
+        return Err(Error::KeyExpired {
+            location: Location::caller(),
+        });
+    }
+
+    Ok(())
+}
+
+fn sign(secret: &HmacSecret, random_id: &str, valid_until: DurationSinceUnixEpoch) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(&secret.0).expect("HMAC accepts a key of any length");
+    mac.update(random_id.as_bytes());
+    mac.update(&valid_until.as_secs().to_be_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Compares two byte slices in constant time, to avoid leaking the HMAC through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Standard JWT claims for a key, so operators can validate tokens issued by `generate_jwt`
+/// with any JWT-aware tooling rather than only the tracker's own key store.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Claims {
+    /// The key id, carried in the standard `sub` claim.
+    pub sub: String,
+    /// Issued-at, as Unix seconds.
+    pub iat: u64,
+    /// Expiry, as Unix seconds.
+    pub exp: u64,
+}
+
+/// # Errors
+///
+/// Will return `Error::InvalidLifetime` if `lifetime` added to the current time would
+/// overflow the clock's max representable time.
+pub fn generate_jwt(lifetime: Duration, secret: &HmacSecret) -> Result<String, Error> {
+    let random_id: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(AUTH_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+
+    let now = Current::now();
+    let valid_until = Current::add(&lifetime).map_err(|_| Error::InvalidLifetime { lifetime })?;
+
+    let claims = Claims {
+        sub: random_id,
+        iat: now.as_secs(),
+        exp: valid_until.as_secs(),
+    };
+
+    Ok(jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(&secret.0),
+    )
+    .expect("claims and header are well-formed and always encode"))
+}
+
+/// # Errors
+///
+/// Will return `Error::JwtVerificationFailed` if `token`'s signature does not match `secret`
+/// or the token is otherwise malformed.
+///
+/// Will return `Error::KeyExpired` if `token`'s `exp` claim is in the past.
+pub fn verify_jwt(token: &str, secret: &HmacSecret) -> Result<Claims, Error> {
+    // `exp` is checked below against the mockable clock instead, so time-travel tests apply.
+    let mut validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
+    validation.validate_exp = false;
+
+    let data = jsonwebtoken::decode::<Claims>(token, &jsonwebtoken::DecodingKey::from_secret(&secret.0), &validation)
+        .map_err(|e| Error::JwtVerificationFailed {
+            source: (Arc::new(e) as Arc<dyn std::error::Error + Send + Sync>).into(),
+        })?;
+
+    if data.claims.exp < Current::now().as_secs() {
+        return Err(Error::KeyExpired {
+            location: Location::caller(),
+        });
+    }
+
+    Ok(data.claims)
+}
+
+/// A backend that decides whether a presented key may announce for a given info hash
+/// and peer IP. [`AuthorizationBackend`] selects between the built-in [`ExpiringKey`]
+/// verifier and an `Authorizer` such as [`GrpcAuthorizer`], for deployments that
+/// manage entitlements elsewhere.
+#[async_trait]
+pub trait Authorizer: Send + Sync {
+    /// # Errors
+    ///
+    /// Will return `Error::ExternalAuthFailure` if the backend is unreachable or denies
+    /// the request.
+    async fn authorize(&self, key: &Key, info_hash: &InfoHash, peer_ip: IpAddr) -> Result<(), Error>;
+}
+
+/// The subset of the generated gRPC client this module depends on, so
+/// [`GrpcAuthorizer`] can be exercised against a mock in tests without a live server.
+#[async_trait]
+pub trait AuthorizationTransport: Send + Sync {
+    async fn check(&self, key: &Key, info_hash: &InfoHash, peer_ip: IpAddr) -> Result<bool, tonic::Status>;
+}
+
+struct CacheEntry {
+    allowed: bool,
+    expires_at: Instant,
+}
+
+/// Default cap on the number of distinct (key, info_hash, peer_ip) combinations a
+/// [`GrpcAuthorizer`] caches at once, used by [`GrpcAuthorizer::new`].
+pub const DEFAULT_MAX_CACHE_ENTRIES: usize = 10_000;
+
+/// An [`Authorizer`] that delegates to an external gRPC service, caching its
+/// allow/deny answers for `ttl` so a high rate of announces doesn't hammer the
+/// remote service on every request.
+///
+/// The cache is bounded to `max_entries`: a client can vary `info_hash` and `peer_ip`
+/// on every announce, so an expiry sweep alone cannot cap how many live entries
+/// accumulate within a single TTL window. Once at capacity, inserting a new
+/// combination evicts the soonest-to-expire entry.
+pub struct GrpcAuthorizer<T: AuthorizationTransport> {
+    transport: T,
+    ttl: Duration,
+    max_entries: usize,
+    cache: Mutex<HashMap<(Key, InfoHash, IpAddr), CacheEntry>>,
+}
+
+impl<T: AuthorizationTransport> GrpcAuthorizer<T> {
+    #[must_use]
+    pub fn new(transport: T, ttl: Duration) -> Self {
+        Self::with_capacity(transport, ttl, DEFAULT_MAX_CACHE_ENTRIES)
+    }
+
+    #[must_use]
+    pub fn with_capacity(transport: T, ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            transport,
+            ttl,
+            max_entries,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: AuthorizationTransport> Authorizer for GrpcAuthorizer<T> {
+    async fn authorize(&self, key: &Key, info_hash: &InfoHash, peer_ip: IpAddr) -> Result<(), Error> {
+        let cache_key = (key.clone(), info_hash.clone(), peer_ip);
+
+        if let Some(entry) = self.cache.lock().unwrap().get(&cache_key) {
+            if entry.expires_at > Instant::now() {
+                return allowed_or_denied(entry.allowed);
+            }
+        }
+
+        let allowed = self
+            .transport
+            .check(key, info_hash, peer_ip)
+            .await
+            .map_err(|status| Error::ExternalAuthFailure {
+                source: (Arc::new(status) as Arc<dyn std::error::Error + Send + Sync>).into(),
+            })?;
+
+        {
+            let mut cache = self.cache.lock().unwrap();
+            let now = Instant::now();
+            // Sweep stale entries on every insert so the cache stays bounded by the
+            // number of distinct (key, info_hash, peer_ip) triples seen within `ttl`,
+            // rather than growing for the life of the process.
+            cache.retain(|_, entry| entry.expires_at > now);
+
+            // The sweep above only removes entries whose TTL has already elapsed, which
+            // does not cap the number of still-live entries within a single TTL window.
+            // Evict the soonest-to-expire entry to enforce a hard ceiling.
+            if cache.len() >= self.max_entries && !cache.contains_key(&cache_key) {
+                if let Some(soonest_to_expire) = cache
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(key, _)| key.clone())
+                {
+                    cache.remove(&soonest_to_expire);
+                }
+            }
+
+            cache.insert(
+                cache_key,
+                CacheEntry {
+                    allowed,
+                    expires_at: now + self.ttl,
+                },
+            );
+        }
+
+        allowed_or_denied(allowed)
+    }
+}
+
+fn allowed_or_denied(allowed: bool) -> Result<(), Error> {
+    if allowed {
+        Ok(())
+    } else {
+        Err(Error::ExternalAuthFailure {
+            source: (Arc::new(std::io::Error::other("external authorizer denied the key")) as Arc<dyn std::error::Error + Send + Sync>)
+                .into(),
+        })
+    }
+}
+
+tonic::include_proto!("torrust.authorization");
+
+/// A concrete [`AuthorizationTransport`] wrapping the client generated from
+/// `proto/authorization.proto`: it sends the presented key plus announce context to a
+/// configured gRPC endpoint.
+pub struct GrpcTransport {
+    client: authorization_client::AuthorizationClient<tonic::transport::Channel>,
+}
+
+impl GrpcTransport {
+    /// # Errors
+    ///
+    /// Will return `Error::ExternalAuthFailure` if `endpoint` cannot be connected to.
+    pub async fn connect(endpoint: String) -> Result<Self, Error> {
+        let client = authorization_client::AuthorizationClient::connect(endpoint)
+            .await
+            .map_err(|e| Error::ExternalAuthFailure {
+                source: (Arc::new(e) as Arc<dyn std::error::Error + Send + Sync>).into(),
+            })?;
+
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl AuthorizationTransport for GrpcTransport {
+    async fn check(&self, key: &Key, info_hash: &InfoHash, peer_ip: IpAddr) -> Result<bool, tonic::Status> {
+        let request = tonic::Request::new(AuthorizeRequest {
+            key: key.to_string(),
+            info_hash: info_hash.to_string(),
+            peer_ip: peer_ip.to_string(),
+        });
+
+        let response = self.client.clone().authorize(request).await?;
+
+        Ok(response.into_inner().allowed)
+    }
+}
+
+/// Selects which backend decides whether a presented key may announce, as configured
+/// by the operator: the tracker's own [`ExpiringKey`] verification with [`verify`], or a
+/// remote [`Authorizer`] such as a [`GrpcAuthorizer`] backed by [`GrpcTransport`].
+/// [`SignedKey`] tokens are not handled here; they are checked with [`verify_signed`].
+pub enum AuthorizationBackend {
+    /// Verify the presented key locally, against its own caveats.
+    Local,
+    /// Delegate the decision to a remote [`Authorizer`].
+    Remote(Arc<dyn Authorizer>),
+}
+
+impl AuthorizationBackend {
+    /// # Errors
+    ///
+    /// Propagates whatever error the selected backend returns: see [`verify`] for the
+    /// `Local` backend, and [`Authorizer::authorize`] for `Remote`.
+    pub async fn authorize(&self, auth_key: &ExpiringKey, context: &VerificationContext<'_>) -> Result<(), Error> {
+        match self {
+            AuthorizationBackend::Local => verify(auth_key, context),
+            AuthorizationBackend::Remote(authorizer) => {
+                authorizer.authorize(&auth_key.key, context.info_hash, context.peer_ip).await
+            }
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 #[allow(dead_code)]
 pub enum Error {
@@ -113,6 +636,25 @@ pub enum Error {
     },
     #[error("Key has expired, {location}")]
     KeyExpired { location: &'static Location<'static> },
+    #[error("Key caveat not satisfied: {caveat:?}, {location}")]
+    CaveatNotSatisfied {
+        caveat: Caveat,
+        location: &'static Location<'static>,
+    },
+    #[error("HMAC secret must be at least {min} bytes long")]
+    HmacSecretTooShort { min: usize },
+    #[error("Signed key signature is invalid, {location}")]
+    InvalidSignature { location: &'static Location<'static> },
+    #[error("External authorization failed: {source}")]
+    ExternalAuthFailure {
+        source: LocatedError<'static, dyn std::error::Error + Send + Sync>,
+    },
+    #[error("Invalid key lifetime: {lifetime:?} would overflow the clock's max representable time")]
+    InvalidLifetime { lifetime: Duration },
+    #[error("JWT could not be verified: {source}")]
+    JwtVerificationFailed {
+        source: LocatedError<'static, dyn std::error::Error + Send + Sync>,
+    },
 }
 
 impl From<r2d2_sqlite::rusqlite::Error> for Error {
@@ -125,11 +667,24 @@ impl From<r2d2_sqlite::rusqlite::Error> for Error {
 
 #[cfg(test)]
 mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
     use std::str::FromStr;
+    use std::sync::Arc;
     use std::time::Duration;
 
     use crate::protocol::clock::{Current, StoppedTime};
+    use crate::protocol::info_hash::InfoHash;
+    use async_trait::async_trait;
+
     use crate::tracker::auth;
+    use crate::tracker::auth::{
+        Authorizer, AuthorizationBackend, AuthorizationTransport, Caveat, GrpcAuthorizer, HmacSecret, IpCidr, SignedKey,
+        VerificationContext,
+    };
+
+    fn context<'a>(info_hash: &'a InfoHash, peer_ip: IpAddr) -> VerificationContext<'a> {
+        VerificationContext { info_hash, peer_ip }
+    }
 
     #[test]
     fn auth_key_from_string() {
@@ -143,8 +698,10 @@ mod tests {
     #[test]
     fn generate_valid_auth_key() {
         let auth_key = auth::generate(Duration::new(9999, 0));
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 
-        assert!(auth::verify(&auth_key).is_ok());
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_ok());
     }
 
     #[test]
@@ -154,15 +711,272 @@ mod tests {
 
         // Make key that is valid for 19 seconds.
         let auth_key = auth::generate(Duration::from_secs(19));
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 
         // Mock the time has passed 10 sec.
         Current::local_add(&Duration::from_secs(10)).unwrap();
 
-        assert!(auth::verify(&auth_key).is_ok());
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_ok());
 
         // Mock the time has passed another 10 sec.
         Current::local_add(&Duration::from_secs(10)).unwrap();
 
-        assert!(auth::verify(&auth_key).is_err());
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_err());
+    }
+
+    #[test]
+    fn key_is_valid_at_the_exact_expiry_instant_and_expired_just_after() {
+        Current::local_set_to_system_time_now();
+
+        let auth_key = auth::generate(Duration::from_secs(10));
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        // Mock the time having advanced to exactly `valid_until`.
+        Current::local_add(&Duration::from_secs(10)).unwrap();
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_ok());
+
+        // One more tick past `valid_until` and the key is expired.
+        Current::local_add(&Duration::from_secs(1)).unwrap();
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_err());
+    }
+
+    #[test]
+    fn key_scoped_to_a_single_info_hash_rejects_other_torrents() {
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let other_info_hash = InfoHash::from_str("1111111111111111111111111111111111111111").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        let mut auth_key = auth::generate(Duration::new(9999, 0));
+        auth_key.caveats.push(Caveat::InfoHash(info_hash.clone()));
+
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_ok());
+        assert!(auth::verify(&auth_key, &context(&other_info_hash, peer_ip)).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn key_scoped_to_a_cidr_rejects_peers_outside_it() {
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let allowed_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 5));
+
+        let mut auth_key = auth::generate(Duration::new(9999, 0));
+        auth_key.caveats.push(Caveat::PeerIp(IpCidr::from_str("10.0.0.0/24").unwrap()));
+
+        assert!(auth::verify(&auth_key, &context(&info_hash, allowed_ip)).is_ok());
+        assert!(auth::verify(&auth_key, &context(&info_hash, other_ip)).is_err());
+    }
+
+    #[test]
+    fn key_scoped_to_a_future_activation_time_rejects_use_before_it() {
+        Current::local_set_to_system_time_now();
+
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let activates_at = Current::add(&Duration::from_secs(10)).unwrap();
+
+        let mut auth_key = auth::generate(Duration::new(9999, 0));
+        auth_key.caveats.push(Caveat::TimeAfter(activates_at));
+
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_err());
+
+        Current::local_add(&Duration::from_secs(20)).unwrap();
+
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_ok());
+    }
+
+    #[test]
+    fn hmac_secret_rejects_secrets_shorter_than_32_bytes() {
+        assert!(HmacSecret::new(vec![0u8; 31]).is_err());
+        assert!(HmacSecret::new(vec![0u8; 32]).is_ok());
+    }
+
+    #[test]
+    fn signed_key_round_trips_through_its_string_form() {
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let signed_key = auth::generate_signed(Duration::new(9999, 0), &secret).unwrap();
+
+        let parsed = SignedKey::from_str(&signed_key.to_string()).unwrap();
+
+        assert!(auth::verify_signed(&parsed, &secret).is_ok());
+    }
+
+    #[test]
+    fn signed_key_is_rejected_when_secret_does_not_match() {
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let other_secret = HmacSecret::new(vec![1u8; 32]).unwrap();
+        let signed_key = auth::generate_signed(Duration::new(9999, 0), &secret).unwrap();
+
+        assert!(auth::verify_signed(&signed_key, &other_secret).is_err());
+    }
+
+    #[test]
+    fn signed_key_is_rejected_once_expired() {
+        Current::local_set_to_system_time_now();
+
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let signed_key = auth::generate_signed(Duration::from_secs(10), &secret).unwrap();
+
+        Current::local_add(&Duration::from_secs(20)).unwrap();
+
+        assert!(auth::verify_signed(&signed_key, &secret).is_err());
+    }
+
+    #[test]
+    fn signed_key_is_valid_at_the_exact_expiry_instant_and_expired_just_after() {
+        Current::local_set_to_system_time_now();
+
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let signed_key = auth::generate_signed(Duration::from_secs(10), &secret).unwrap();
+
+        // Mock the time having advanced to exactly `valid_until`.
+        Current::local_add(&Duration::from_secs(10)).unwrap();
+        assert!(auth::verify_signed(&signed_key, &secret).is_ok());
+
+        // One more tick past `valid_until` and the key is expired.
+        Current::local_add(&Duration::from_secs(1)).unwrap();
+        assert!(auth::verify_signed(&signed_key, &secret).is_err());
+    }
+
+    #[test]
+    fn generate_signed_rejects_a_lifetime_that_would_overflow_the_clock() {
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+
+        assert!(auth::generate_signed(Duration::MAX, &secret).is_err());
+        assert!(auth::generate_signed(Duration::new(9999, 0), &secret).is_ok());
+    }
+
+    #[test]
+    fn try_generate_rejects_a_lifetime_that_would_overflow_the_clock() {
+        assert!(auth::try_generate(Duration::MAX).is_err());
+        assert!(auth::try_generate(Duration::new(9999, 0)).is_ok());
+    }
+
+    #[test]
+    fn permanent_key_is_always_valid_and_displays_as_never_expiring() {
+        let auth_key = auth::generate_permanent();
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        assert!(auth::verify(&auth_key, &context(&info_hash, peer_ip)).is_ok());
+        assert!(auth_key.to_string().ends_with("valid until never"));
+    }
+
+    #[test]
+    fn jwt_round_trips_and_exposes_the_key_id_as_subject() {
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let token = auth::generate_jwt(Duration::new(9999, 0), &secret).unwrap();
+
+        let claims = auth::verify_jwt(&token, &secret).unwrap();
+
+        assert_eq!(claims.sub.len(), crate::protocol::common::AUTH_KEY_LENGTH);
+        assert!(claims.exp > claims.iat);
+    }
+
+    #[test]
+    fn jwt_is_rejected_when_signed_with_a_different_secret() {
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let other_secret = HmacSecret::new(vec![1u8; 32]).unwrap();
+        let token = auth::generate_jwt(Duration::new(9999, 0), &secret).unwrap();
+
+        assert!(auth::verify_jwt(&token, &other_secret).is_err());
+    }
+
+    #[test]
+    fn jwt_is_rejected_once_expired() {
+        Current::local_set_to_system_time_now();
+
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+        let token = auth::generate_jwt(Duration::from_secs(10), &secret).unwrap();
+
+        Current::local_add(&Duration::from_secs(20)).unwrap();
+
+        assert!(auth::verify_jwt(&token, &secret).is_err());
+    }
+
+    #[test]
+    fn generate_jwt_rejects_a_lifetime_that_would_overflow_the_clock() {
+        let secret = HmacSecret::new(vec![0u8; 32]).unwrap();
+
+        assert!(auth::generate_jwt(Duration::MAX, &secret).is_err());
+        assert!(auth::generate_jwt(Duration::new(9999, 0), &secret).is_ok());
+    }
+
+    struct AlwaysTransport(bool);
+
+    #[async_trait]
+    impl AuthorizationTransport for AlwaysTransport {
+        async fn check(&self, _key: &auth::Key, _info_hash: &InfoHash, _peer_ip: IpAddr) -> Result<bool, tonic::Status> {
+            Ok(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn grpc_authorizer_allows_when_the_remote_service_allows() {
+        let authorizer = GrpcAuthorizer::new(AlwaysTransport(true), Duration::from_secs(60));
+        let key = auth::Key::from_str("YZSl4lMZupRuOpSRC3krIKR5BPB14nrJ").unwrap();
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        assert!(authorizer.authorize(&key, &info_hash, peer_ip).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn grpc_authorizer_denies_when_the_remote_service_denies() {
+        let authorizer = GrpcAuthorizer::new(AlwaysTransport(false), Duration::from_secs(60));
+        let key = auth::Key::from_str("YZSl4lMZupRuOpSRC3krIKR5BPB14nrJ").unwrap();
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        assert!(authorizer.authorize(&key, &info_hash, peer_ip).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn grpc_authorizer_evicts_expired_cache_entries_on_insert() {
+        let authorizer = GrpcAuthorizer::new(AlwaysTransport(true), Duration::from_millis(1));
+        let key = auth::Key::from_str("YZSl4lMZupRuOpSRC3krIKR5BPB14nrJ").unwrap();
+        let other_key = auth::Key::from_str("AZSl4lMZupRuOpSRC3krIKR5BPB14nrJ").unwrap();
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        authorizer.authorize(&key, &info_hash, peer_ip).await.unwrap();
+        assert_eq!(authorizer.cache.lock().unwrap().len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        // Inserting a second, unrelated entry should sweep the now-expired first one.
+        authorizer.authorize(&other_key, &info_hash, peer_ip).await.unwrap();
+        assert_eq!(authorizer.cache.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn grpc_authorizer_cache_never_exceeds_its_capacity() {
+        let authorizer = GrpcAuthorizer::with_capacity(AlwaysTransport(true), Duration::from_secs(60), 3);
+        let key = auth::Key::from_str("YZSl4lMZupRuOpSRC3krIKR5BPB14nrJ").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        // A well-behaved TTL alone wouldn't cap this: none of these entries expire
+        // before the next one is inserted, yet the cache must still stay bounded.
+        for i in 0..10 {
+            let info_hash = InfoHash::from_str(&format!("{i:040x}")).unwrap();
+            authorizer.authorize(&key, &info_hash, peer_ip).await.unwrap();
+            assert!(authorizer.cache.lock().unwrap().len() <= 3);
+        }
+
+        assert_eq!(authorizer.cache.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn authorization_backend_dispatches_to_the_configured_backend() {
+        let info_hash = InfoHash::from_str("0000000000000000000000000000000000000000").unwrap();
+        let peer_ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        let auth_key = auth::generate(Duration::new(9999, 0));
+        let local = AuthorizationBackend::Local;
+        assert!(local.authorize(&auth_key, &context(&info_hash, peer_ip)).await.is_ok());
+
+        let remote = AuthorizationBackend::Remote(Arc::new(GrpcAuthorizer::new(AlwaysTransport(false), Duration::from_secs(60))));
+        assert!(remote.authorize(&auth_key, &context(&info_hash, peer_ip)).await.is_err());
+    }
+}